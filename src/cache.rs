@@ -0,0 +1,64 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::sync::{Arc, Mutex};
+
+/// A persistent cache mapping Roblox user IDs to account creation years,
+/// backed by a local SQLite database.
+pub struct AccountAgeCache {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl AccountAgeCache {
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS account_age (
+                user_id INTEGER PRIMARY KEY,
+                creation_year INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs on a blocking-pool thread so a cache hit/miss doesn't stall the
+    /// tokio worker thread a concurrent member task is running on.
+    pub async fn get(&self, user_id: u64) -> rusqlite::Result<Option<u64>> {
+        let conn = Arc::clone(&self.conn);
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().unwrap();
+
+            conn.query_row(
+                "SELECT creation_year FROM account_age WHERE user_id = ?1",
+                params![user_id],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+        .await
+        .expect("account age cache task panicked")
+    }
+
+    /// Runs on a blocking-pool thread so a cache write doesn't stall the
+    /// tokio worker thread a concurrent member task is running on.
+    pub async fn insert(&self, user_id: u64, creation_year: u64) -> rusqlite::Result<()> {
+        let conn = Arc::clone(&self.conn);
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().unwrap();
+
+            conn.execute(
+                "INSERT OR REPLACE INTO account_age (user_id, creation_year) VALUES (?1, ?2)",
+                params![user_id, creation_year],
+            )?;
+
+            Ok(())
+        })
+        .await
+        .expect("account age cache task panicked")
+    }
+}
@@ -0,0 +1,32 @@
+use serde_json::json;
+
+/// Posts rank-change summaries to a Discord webhook.
+///
+/// `notify` swallows send errors after logging them, since a notification
+/// failure shouldn't fail the scan it's reporting on.
+pub struct DiscordNotifier {
+    webhook_url: String,
+    client: reqwest::Client,
+}
+
+impl DiscordNotifier {
+    pub fn new(webhook_url: String) -> Self {
+        Self {
+            webhook_url,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn notify(&self, content: &str) {
+        let result = self
+            .client
+            .post(&self.webhook_url)
+            .json(&json!({ "content": content }))
+            .send()
+            .await;
+
+        if let Err(e) = result {
+            eprintln!("Discord webhook notification failed: {e}");
+        }
+    }
+}
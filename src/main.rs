@@ -1,12 +1,27 @@
+mod cache;
+mod discord;
+mod metrics;
+mod rate_limiter;
+mod report;
+
+use cache::AccountAgeCache;
+use discord::DiscordNotifier;
+use futures::stream::{self, StreamExt};
+use metrics::Metrics;
+use rate_limiter::RateLimiter;
+use report::PlannedChange;
 use roboat::{Client, ClientBuilder, Limit, RoboatError};
 use safelog::Sensitive;
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 use std::{env, fs};
-use tokio::time::Duration;
+use tokio::time::{Duration, Interval};
 
 const PAGE_LIMIT: Limit = Limit::Hundred;
-const TOO_MANY_REQUESTS_COOLDOWN: Duration = Duration::from_secs(60);
 
 const ACCOUNT_AGE_RETRIES: usize = 5;
 const SET_GROUP_MEMBER_ROLE_RETRIES: usize = 5;
@@ -15,12 +30,67 @@ const SET_GROUP_MEMBER_ROLE_RETRIES: usize = 5;
 /// We can ignore this.
 const USER_ALREADY_HAS_ROLE_ROBLOX_ERROR_CODE: u16 = 26;
 
+/// Default number of members processed concurrently per page when
+/// `concurrency` isn't set in the config.
+const DEFAULT_CONCURRENCY: usize = 10;
+/// Default token-bucket capacity when `rate_limit_capacity` isn't set.
+const DEFAULT_RATE_LIMIT_CAPACITY: u32 = 10;
+/// Default token-bucket refill rate when `rate_limit_refill_per_sec` isn't set.
+const DEFAULT_RATE_LIMIT_REFILL_PER_SEC: u32 = 10;
+/// Default minimum backoff after a `TooManyRequests` hit when
+/// `rate_limit_cooldown_secs` isn't set.
+const DEFAULT_RATE_LIMIT_COOLDOWN_SECS: u64 = 60;
+/// Default path for the dry-run CSV report when `dry_run_report_path` isn't set.
+const DEFAULT_DRY_RUN_REPORT_PATH: &str = "dry_run_report.csv";
+/// CLI flag that forces dry-run mode regardless of the config file.
+const DRY_RUN_FLAG: &str = "--dry-run";
+
 #[derive(Deserialize, Debug)]
 struct Config {
+    /// The cookie that will be used to authenticate the bot. Shared by every
+    /// group in `groups`.
+    roblosecurity: Sensitive<String>,
+    /// The groups to rank, each scanned with its own roles and rank rules.
+    groups: Vec<GroupConfig>,
+    /// If set, the bot re-runs the full scan on this cadence instead of
+    /// exiting after a single pass.
+    scan_interval_secs: Option<u64>,
+    /// How many members are processed concurrently per page. Defaults to
+    /// [`DEFAULT_CONCURRENCY`].
+    concurrency: Option<usize>,
+    /// The number of tokens in the shared rate limiter's bucket. Defaults to
+    /// [`DEFAULT_RATE_LIMIT_CAPACITY`].
+    rate_limit_capacity: Option<u32>,
+    /// How many tokens per second the shared rate limiter refills. Defaults
+    /// to [`DEFAULT_RATE_LIMIT_REFILL_PER_SEC`].
+    rate_limit_refill_per_sec: Option<u32>,
+    /// Minimum time every in-flight member holds off after a
+    /// `TooManyRequests` hit, regardless of `rate_limit_refill_per_sec`.
+    /// Defaults to [`DEFAULT_RATE_LIMIT_COOLDOWN_SECS`].
+    rate_limit_cooldown_secs: Option<u64>,
+    /// If set, account creation years are cached on disk at this path so
+    /// repeat scans don't have to re-hit `user_details` for known users.
+    cache_path: Option<String>,
+    /// If true, the scan computes each member's target role but never calls
+    /// `set_group_member_role`, instead writing a CSV report of the planned
+    /// changes to `dry_run_report_path`.
+    #[serde(default)]
+    dry_run: bool,
+    /// Where the dry-run CSV report is written. Defaults to
+    /// [`DEFAULT_DRY_RUN_REPORT_PATH`].
+    dry_run_report_path: Option<String>,
+    /// If set, a Prometheus `/metrics` endpoint is served on this address
+    /// (e.g. `"0.0.0.0:9898"`) for the lifetime of the process.
+    metrics_addr: Option<String>,
+    /// If set, a summary of each group's rank changes is posted to this
+    /// Discord webhook as the bot works.
+    discord_webhook_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GroupConfig {
     /// The group ID of the group to scan.
     group_id: u64,
-    /// The cookie that will be used to authenticate the bot.
-    roblosecurity: Sensitive<String>,
     /// Which ranks to scan for members.
     scanned_roles: Vec<String>,
     /// The key is the role name, and the value is a list of years
@@ -42,46 +112,268 @@ enum Error {
     RoleNotFound(String),
     #[error("{0} endpoint exceeded retry limit")]
     EndpointExceededRetryLimit(String),
+    #[error("Account age cache error: {0}")]
+    CacheError(#[from] rusqlite::Error),
+    #[error("Dry-run report error: {0}")]
+    ReportError(#[from] csv::Error),
+    #[error("Invalid metrics_addr: {0}")]
+    InvalidMetricsAddr(#[from] std::net::AddrParseError),
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = match env::args().nth(1) {
+    let mut config = match env::args().skip(1).find(|arg| arg != DRY_RUN_FLAG) {
         Some(file_path) => serde_json::from_str::<Config>(&fs::read_to_string(file_path)?)?,
         None => return Err(Error::ConfigFileNotProvided.into()),
     };
 
+    if env::args().any(|arg| arg == DRY_RUN_FLAG) {
+        config.dry_run = true;
+    }
+
     let client = ClientBuilder::new()
         .roblosecurity(config.roblosecurity.into_inner())
         .build();
 
+    let metrics = Metrics::new();
+
+    if let Some(metrics_addr) = &config.metrics_addr {
+        let addr = metrics_addr.parse().map_err(Error::InvalidMetricsAddr)?;
+        metrics::serve(Arc::clone(&metrics), addr);
+    }
+
+    let scan_interval_secs = config.scan_interval_secs;
+
+    match scan_interval_secs {
+        None => run_scan(&client, &config, &AtomicBool::new(false), &metrics).await?,
+        Some(interval_secs) => run_daemon(client, config, interval_secs, metrics).await?,
+    }
+
+    Ok(())
+}
+
+/// Runs the scan on a fixed cadence until a SIGINT is received, at which
+/// point the in-flight member is finished before shutting down.
+///
+/// A scan that overruns the interval is never allowed to overlap with the
+/// next tick: if the previous scan is still running when the timer fires,
+/// the tick is skipped and logged instead of starting a second scan.
+async fn run_daemon(
+    client: Client,
+    config: Config,
+    interval_secs: u64,
+    metrics: Arc<Metrics>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = Arc::new(client);
+    let config = Arc::new(config);
+    let scan_in_progress = Arc::new(AtomicBool::new(false));
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    let mut interval: Interval = tokio::time::interval(Duration::from_secs(interval_secs));
+    let mut scan_handle: Option<tokio::task::JoinHandle<()>> = None;
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {
+                println!("Shutdown requested, finishing current member before exiting...");
+                shutdown.store(true, Ordering::SeqCst);
+                break;
+            }
+            _ = interval.tick() => {
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                if scan_in_progress.swap(true, Ordering::SeqCst) {
+                    println!("Previous scan is still in progress, skipping this tick.");
+                    continue;
+                }
+
+                let client = Arc::clone(&client);
+                let config = Arc::clone(&config);
+                let scan_in_progress = Arc::clone(&scan_in_progress);
+                let shutdown = Arc::clone(&shutdown);
+                let metrics = Arc::clone(&metrics);
+
+                scan_handle = Some(tokio::spawn(async move {
+                    if let Err(e) = run_scan(&client, &config, &shutdown, &metrics).await {
+                        eprintln!("Scan failed: {e}");
+                    }
+
+                    scan_in_progress.store(false, Ordering::SeqCst);
+                }));
+            }
+        }
+    }
+
+    // Let the in-flight scan finish the member it's on instead of having the
+    // runtime tear its task down out from under it.
+    if let Some(handle) = scan_handle {
+        if let Err(e) = handle.await {
+            eprintln!("Scan task panicked: {e}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs a single full pass over every group in `config.groups`, re-reading
+/// each group's `group_roles` so role renames/additions are picked up
+/// without a restart.
+///
+/// `shutdown` is checked between members so a requested shutdown finishes
+/// the current member rather than aborting mid-assignment.
+async fn run_scan(
+    client: &Client,
+    config: &Config,
+    shutdown: &AtomicBool,
+    metrics: &Metrics,
+) -> Result<(), Error> {
+    let scan_started_at = Instant::now();
+
+    let rate_limiter = RateLimiter::new(
+        config
+            .rate_limit_capacity
+            .unwrap_or(DEFAULT_RATE_LIMIT_CAPACITY),
+        config
+            .rate_limit_refill_per_sec
+            .unwrap_or(DEFAULT_RATE_LIMIT_REFILL_PER_SEC),
+        config
+            .rate_limit_cooldown_secs
+            .unwrap_or(DEFAULT_RATE_LIMIT_COOLDOWN_SECS),
+    );
+
+    let cache = match &config.cache_path {
+        Some(path) => Some(AccountAgeCache::open(path)?),
+        None => None,
+    };
+
+    let discord = config.discord_webhook_url.clone().map(DiscordNotifier::new);
+
+    let mut planned_changes = Vec::new();
+
+    // We scan each group independently: an error in one group (e.g. a
+    // misconfigured role) is reported and skipped rather than aborting the
+    // sync for every other group.
+    for group in &config.groups {
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let applied_before = metrics.role_assignments_applied.load(Ordering::Relaxed);
+        let skipped_before = metrics
+            .role_assignments_skipped_already_has_role
+            .load(Ordering::Relaxed);
+
+        let scan_result = scan_group(
+            client,
+            config,
+            group,
+            &rate_limiter,
+            cache.as_ref(),
+            metrics,
+            shutdown,
+            &mut planned_changes,
+        )
+        .await;
+
+        if let Err(e) = &scan_result {
+            eprintln!("Group {} failed, skipping: {e}", group.group_id);
+        }
+
+        if let Some(discord) = &discord {
+            if !config.dry_run {
+                let applied =
+                    metrics.role_assignments_applied.load(Ordering::Relaxed) - applied_before;
+                let skipped = metrics
+                    .role_assignments_skipped_already_has_role
+                    .load(Ordering::Relaxed)
+                    - skipped_before;
+
+                let summary = match &scan_result {
+                    Ok(()) => format!(
+                        "Finished syncing group {}: assigned {applied} role(s), skipped {skipped} (already had role).",
+                        group.group_id
+                    ),
+                    Err(e) => format!(
+                        "Group {} failed after assigning {applied} role(s), skipping {skipped} (already had role): {e}",
+                        group.group_id
+                    ),
+                };
+
+                discord.notify(&summary).await;
+            }
+        }
+    }
+
+    if config.dry_run {
+        let report_path = config
+            .dry_run_report_path
+            .as_deref()
+            .unwrap_or(DEFAULT_DRY_RUN_REPORT_PATH);
+
+        report::write_report(Path::new(report_path), &planned_changes)?;
+
+        println!(
+            "Dry run complete: wrote {} planned change(s) to {}",
+            planned_changes.len(),
+            report_path
+        );
+    }
+
+    metrics
+        .last_scan_duration_secs
+        .store(scan_started_at.elapsed().as_secs(), Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Scans a single group's ranks, assigning roles (or recording planned
+/// changes into `planned_changes` in dry-run mode).
+#[allow(clippy::too_many_arguments)]
+async fn scan_group(
+    client: &Client,
+    config: &Config,
+    group: &GroupConfig,
+    rate_limiter: &RateLimiter,
+    cache: Option<&AccountAgeCache>,
+    metrics: &Metrics,
+    shutdown: &AtomicBool,
+    planned_changes: &mut Vec<PlannedChange>,
+) -> Result<(), Error> {
     // We make basically a reverse of `role_year_pairs` so that we can
     // easily get the role name from the year.
-    let year_role_pairs = reverse_role_year_pairs(&config.role_year_pairs);
+    let year_role_pairs = reverse_role_year_pairs(&group.role_year_pairs);
 
     let role_id_lookup = generate_role_id_lookup(
-        &client,
-        config.group_id,
-        &config.scanned_roles,
-        &config.role_year_pairs,
-        config.wildcard_role.clone(),
+        client,
+        group.group_id,
+        &group.scanned_roles,
+        &group.role_year_pairs,
+        group.wildcard_role.clone(),
     )
     .await?;
 
+    let concurrency = config.concurrency.unwrap_or(DEFAULT_CONCURRENCY);
+
     // We loop through each rank we need to scan.
-    for role_to_scan in config.scanned_roles {
+    for role_to_scan in &group.scanned_roles {
         // We now page through the members of the group and assign them.
         // We go in pages of 100 at a time.
 
         let role_to_scan_id = role_id_lookup
-            .get(&role_to_scan)
-            .ok_or(Error::RoleNotFound(role_to_scan.clone()))?;
+            .get(role_to_scan)
+            .ok_or_else(|| Error::RoleNotFound(role_to_scan.clone()))?;
 
         let mut next_cursor = None;
 
         loop {
+            if shutdown.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+
             let (member_ids, new_cursor) =
-                page_of_members(&client, config.group_id, *role_to_scan_id, next_cursor).await?;
+                page_of_members(client, group.group_id, *role_to_scan_id, next_cursor).await?;
 
             if member_ids.is_empty() {
                 break;
@@ -89,41 +381,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             next_cursor = new_cursor;
 
-            // We now loop through each member and assign them their role based on their account age.
-            for member_id in member_ids {
-                let account_age = year_created(&client, member_id).await?;
-
-                let corresponding_role = year_role_pairs.get(&account_age);
-
-                match corresponding_role {
-                    Some(role) => {
-                        let role_id = role_id_lookup.get(role).unwrap();
-
-                        set_group_member_role(&client, config.group_id, member_id, *role_id)
-                            .await?;
-
-                        println!(
-                            "Assigned role {} to user {} (account age: {})",
-                            role, member_id, account_age
-                        );
-                    }
-                    None => {
-                        // If the user doesn't have a corresponding role, we assign them the wildcard role.
-
-                        let role = config.wildcard_role.clone();
-                        let role_id = role_id_lookup.get(&role).unwrap();
-
-                        set_group_member_role(&client, config.group_id, member_id, *role_id)
-                            .await?;
+            // We now process the page's members concurrently (bounded by
+            // `concurrency`), all sharing the same rate limiter.
+            let results: Vec<Result<Option<PlannedChange>, Error>> = stream::iter(member_ids)
+                .map(|member_id| {
+                    let process = process_member(
+                        client,
+                        config,
+                        group,
+                        rate_limiter,
+                        cache,
+                        metrics,
+                        &role_id_lookup,
+                        &year_role_pairs,
+                        role_to_scan,
+                        member_id,
+                    );
+
+                    // Members not yet started when a shutdown is requested are
+                    // skipped; the ones already in flight finish.
+                    async move {
+                        if shutdown.load(Ordering::SeqCst) {
+                            return Ok(None);
+                        }
 
-                        println!(
-                            "Assigned role {} to user {} (account age: {})",
-                            &config.wildcard_role, member_id, account_age
-                        );
+                        process.await
                     }
+                })
+                .buffer_unordered(concurrency)
+                .collect()
+                .await;
+
+            for result in results {
+                if let Some(change) = result? {
+                    planned_changes.push(change);
                 }
             }
 
+            if shutdown.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+
             if next_cursor.is_none() {
                 break;
             }
@@ -133,6 +431,64 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Looks up a single member's account age and either assigns them their
+/// corresponding role (or the wildcard role if none matches), or, in dry-run
+/// mode, returns the change that would have been made without applying it.
+#[allow(clippy::too_many_arguments)]
+async fn process_member(
+    client: &Client,
+    config: &Config,
+    group: &GroupConfig,
+    rate_limiter: &RateLimiter,
+    cache: Option<&AccountAgeCache>,
+    metrics: &Metrics,
+    role_id_lookup: &HashMap<String, u64>,
+    year_role_pairs: &HashMap<u64, String>,
+    current_role: &str,
+    member_id: u64,
+) -> Result<Option<PlannedChange>, Error> {
+    let account_age = year_created(client, rate_limiter, cache, metrics, member_id).await?;
+
+    metrics.members_scanned.fetch_add(1, Ordering::Relaxed);
+
+    let corresponding_role = year_role_pairs.get(&account_age);
+
+    let (target_role, match_kind) = match corresponding_role {
+        Some(role) => (role.clone(), "matched"),
+        None => (group.wildcard_role.clone(), "wildcard"),
+    };
+
+    if config.dry_run {
+        return Ok(Some(PlannedChange {
+            group_id: group.group_id,
+            user_id: member_id,
+            current_role: current_role.to_owned(),
+            target_role,
+            account_year: account_age,
+            match_kind,
+        }));
+    }
+
+    let role_id = role_id_lookup.get(&target_role).unwrap();
+
+    set_group_member_role(
+        client,
+        rate_limiter,
+        metrics,
+        group.group_id,
+        member_id,
+        *role_id,
+    )
+    .await?;
+
+    println!(
+        "Assigned role {} to user {} (account age: {})",
+        target_role, member_id, account_age
+    );
+
+    Ok(None)
+}
+
 fn reverse_role_year_pairs(role_year_pairs: &HashMap<String, Vec<u64>>) -> HashMap<u64, String> {
     let mut reversed_map = HashMap::new();
 
@@ -210,22 +566,54 @@ async fn page_of_members(
     Ok((member_ids, next_cursor))
 }
 
-async fn year_created(client: &Client, user_id: u64) -> Result<u64, Error> {
+async fn year_created(
+    client: &Client,
+    rate_limiter: &RateLimiter,
+    cache: Option<&AccountAgeCache>,
+    metrics: &Metrics,
+    user_id: u64,
+) -> Result<u64, Error> {
+    if let Some(cache) = cache {
+        if let Some(creation_year) = cache.get(user_id).await? {
+            return Ok(creation_year);
+        }
+    }
+
     let mut retries_remaining = ACCOUNT_AGE_RETRIES;
 
     loop {
+        rate_limiter.acquire().await;
+
         match client.user_details(user_id).await {
-            Ok(user_details) => return Ok(user_details.created_at[0..4].parse().unwrap()),
+            Ok(user_details) => {
+                let creation_year: u64 = user_details.created_at[0..4].parse().unwrap();
+
+                if let Some(cache) = cache {
+                    cache.insert(user_id, creation_year).await?;
+                }
+
+                return Ok(creation_year);
+            }
             Err(e) => {
                 if retries_remaining == 0 {
+                    metrics
+                        .account_age_retry_exhaustion_failures
+                        .fetch_add(1, Ordering::Relaxed);
+
                     return Err(Error::EndpointExceededRetryLimit("Account age".to_owned()));
                 }
 
                 retries_remaining -= 1;
 
-                // If the error is too many requests, then we sleep for 60 seconds.
+                // If the error is too many requests, drain the bucket so every
+                // in-flight task backs off together instead of each sleeping
+                // an independent fixed cooldown.
                 if let RoboatError::TooManyRequests = e {
-                    tokio::time::sleep(TOO_MANY_REQUESTS_COOLDOWN).await;
+                    metrics
+                        .too_many_requests_hits
+                        .fetch_add(1, Ordering::Relaxed);
+
+                    rate_limiter.drain().await;
                 }
             }
         }
@@ -234,6 +622,8 @@ async fn year_created(client: &Client, user_id: u64) -> Result<u64, Error> {
 
 async fn set_group_member_role(
     client: &Client,
+    rate_limiter: &RateLimiter,
+    metrics: &Metrics,
     group_id: u64,
     user_id: u64,
     role_id: u64,
@@ -241,13 +631,25 @@ async fn set_group_member_role(
     let mut retries_remaining = SET_GROUP_MEMBER_ROLE_RETRIES;
 
     loop {
+        rate_limiter.acquire().await;
+
         match client
             .set_group_member_role(user_id, group_id, role_id)
             .await
         {
-            Ok(_) => return Ok(()),
+            Ok(_) => {
+                metrics
+                    .role_assignments_applied
+                    .fetch_add(1, Ordering::Relaxed);
+
+                return Ok(());
+            }
             Err(e) => {
                 if retries_remaining == 0 {
+                    metrics
+                        .set_group_member_role_retry_exhaustion_failures
+                        .fetch_add(1, Ordering::Relaxed);
+
                     return Err(Error::EndpointExceededRetryLimit(
                         "Set group member role".to_owned(),
                     ));
@@ -260,10 +662,18 @@ async fn set_group_member_role(
                         return Err(Error::NonRecoverableRoboatError(e))
                     }
                     RoboatError::TooManyRequests => {
-                        tokio::time::sleep(TOO_MANY_REQUESTS_COOLDOWN).await;
+                        metrics
+                            .too_many_requests_hits
+                            .fetch_add(1, Ordering::Relaxed);
+
+                        rate_limiter.drain().await;
                     }
                     RoboatError::UnknownRobloxErrorCode { code, .. } => {
                         if code == USER_ALREADY_HAS_ROLE_ROBLOX_ERROR_CODE {
+                            metrics
+                                .role_assignments_skipped_already_has_role
+                                .fetch_add(1, Ordering::Relaxed);
+
                             return Ok(());
                         }
                     }
@@ -0,0 +1,95 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+
+/// Prometheus-format counters and gauges for scan observability.
+///
+/// Fields are plain atomics so any call site can increment them without
+/// needing a lock, and they accumulate across scans for the lifetime of the
+/// process.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub members_scanned: AtomicU64,
+    pub role_assignments_applied: AtomicU64,
+    pub role_assignments_skipped_already_has_role: AtomicU64,
+    pub too_many_requests_hits: AtomicU64,
+    pub account_age_retry_exhaustion_failures: AtomicU64,
+    pub set_group_member_role_retry_exhaustion_failures: AtomicU64,
+    pub last_scan_duration_secs: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "# HELP classics_ranking_bot_members_scanned_total Total members scanned.\n\
+             # TYPE classics_ranking_bot_members_scanned_total counter\n\
+             classics_ranking_bot_members_scanned_total {}\n\
+             # HELP classics_ranking_bot_role_assignments_applied_total Role assignments applied.\n\
+             # TYPE classics_ranking_bot_role_assignments_applied_total counter\n\
+             classics_ranking_bot_role_assignments_applied_total {}\n\
+             # HELP classics_ranking_bot_role_assignments_skipped_total Assignments skipped because the user already had the role.\n\
+             # TYPE classics_ranking_bot_role_assignments_skipped_total counter\n\
+             classics_ranking_bot_role_assignments_skipped_total {}\n\
+             # HELP classics_ranking_bot_too_many_requests_total TooManyRequests responses hit.\n\
+             # TYPE classics_ranking_bot_too_many_requests_total counter\n\
+             classics_ranking_bot_too_many_requests_total {}\n\
+             # HELP classics_ranking_bot_retry_exhaustion_total Requests that exhausted their retry budget, by endpoint.\n\
+             # TYPE classics_ranking_bot_retry_exhaustion_total counter\n\
+             classics_ranking_bot_retry_exhaustion_total{{endpoint=\"account_age\"}} {}\n\
+             classics_ranking_bot_retry_exhaustion_total{{endpoint=\"set_group_member_role\"}} {}\n\
+             # HELP classics_ranking_bot_last_scan_duration_seconds Duration of the last full scan, in seconds.\n\
+             # TYPE classics_ranking_bot_last_scan_duration_seconds gauge\n\
+             classics_ranking_bot_last_scan_duration_seconds {}\n",
+            self.members_scanned.load(Ordering::Relaxed),
+            self.role_assignments_applied.load(Ordering::Relaxed),
+            self.role_assignments_skipped_already_has_role
+                .load(Ordering::Relaxed),
+            self.too_many_requests_hits.load(Ordering::Relaxed),
+            self.account_age_retry_exhaustion_failures
+                .load(Ordering::Relaxed),
+            self.set_group_member_role_retry_exhaustion_failures
+                .load(Ordering::Relaxed),
+            self.last_scan_duration_secs.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Spins up a small HTTP server exposing `/metrics` in Prometheus text
+/// format, bound to `addr`. Runs for the lifetime of the process.
+pub fn serve(metrics: Arc<Metrics>, addr: SocketAddr) {
+    tokio::spawn(async move {
+        let make_svc = make_service_fn(move |_conn| {
+            let metrics = Arc::clone(&metrics);
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let metrics = Arc::clone(&metrics);
+
+                    async move {
+                        let response = if req.uri().path() == "/metrics" {
+                            Response::new(Body::from(metrics.render()))
+                        } else {
+                            let mut not_found = Response::new(Body::from("Not Found"));
+                            *not_found.status_mut() = StatusCode::NOT_FOUND;
+                            not_found
+                        };
+
+                        Ok::<_, Infallible>(response)
+                    }
+                }))
+            }
+        });
+
+        if let Err(e) = Server::bind(&addr).serve(make_svc).await {
+            eprintln!("Metrics server error: {e}");
+        }
+    });
+}
@@ -0,0 +1,173 @@
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+/// Fallback sleep used when `refill_per_sec` is `0` (the bucket never
+/// refills), so `acquire` polls instead of computing a division by zero.
+const NEVER_REFILL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A token-bucket rate limiter shared across the outbound `user_details` and
+/// `set_group_member_role` calls of a scan.
+///
+/// The bucket holds `capacity` tokens and refills at `refill_per_sec` tokens
+/// per second. Each of those calls must [`acquire`](Self::acquire) a token
+/// before making its request, so bounded-concurrency member processing can't
+/// outrun Roblox's own rate limits. The `group_roles` and member-page
+/// requests do not go through the limiter.
+#[derive(Debug)]
+pub struct RateLimiter {
+    inner: Mutex<Bucket>,
+    capacity: f64,
+    refill_per_sec: f64,
+    cooldown: Duration,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    /// Set into the future by [`RateLimiter::drain`] so refilling actually
+    /// pauses for `cooldown`, instead of resuming at the steady-state rate
+    /// on the very next `acquire`.
+    cooldown_until: Instant,
+}
+
+impl RateLimiter {
+    /// `cooldown_secs` is the minimum time every caller backs off together
+    /// after a `TooManyRequests` response, regardless of `refill_per_sec`.
+    pub fn new(capacity: u32, refill_per_sec: u32, cooldown_secs: u64) -> Arc<Self> {
+        let now = Instant::now();
+
+        Arc::new(Self {
+            inner: Mutex::new(Bucket {
+                tokens: capacity as f64,
+                last_refill: now,
+                cooldown_until: now,
+            }),
+            capacity: capacity as f64,
+            refill_per_sec: refill_per_sec as f64,
+            cooldown: Duration::from_secs(cooldown_secs),
+        })
+    }
+
+    /// Waits until a token is available, then consumes it.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.inner.lock().await;
+                let now = Instant::now();
+
+                if bucket.cooldown_until > now {
+                    Some(bucket.cooldown_until - now)
+                } else {
+                    self.refill(&mut bucket);
+
+                    if bucket.tokens >= 1.0 {
+                        bucket.tokens -= 1.0;
+                        None
+                    } else if self.refill_per_sec <= 0.0 {
+                        // A `0` refill rate means the bucket never refills on
+                        // its own; poll instead of dividing by zero.
+                        Some(NEVER_REFILL_POLL_INTERVAL)
+                    } else {
+                        let deficit = 1.0 - bucket.tokens;
+                        Some(Duration::from_secs_f64(deficit / self.refill_per_sec))
+                    }
+                }
+            };
+
+            match wait {
+                None => return,
+                Some(duration) => tokio::time::sleep(duration).await,
+            }
+        }
+    }
+
+    /// Drains the bucket to zero and holds it empty for `cooldown`, so every
+    /// in-flight task backs off together for a real minimum duration instead
+    /// of resuming as soon as the steady-state refill rate allows.
+    pub async fn drain(&self) {
+        let mut bucket = self.inner.lock().await;
+        bucket.tokens = 0.0;
+        let now = Instant::now();
+        bucket.cooldown_until = now + self.cooldown;
+        // Refilling starts from the end of the cooldown, not from now.
+        bucket.last_refill = bucket.cooldown_until;
+    }
+
+    fn refill(&self, bucket: &mut Bucket) {
+        let elapsed = bucket.last_refill.elapsed().as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.last_refill = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn acquire_consumes_a_token_when_one_is_available() {
+        let limiter = RateLimiter::new(1, 1, 0);
+
+        limiter.acquire().await;
+
+        let bucket = limiter.inner.lock().await;
+        assert_eq!(bucket.tokens, 0.0);
+    }
+
+    #[tokio::test]
+    async fn refill_adds_tokens_proportional_to_elapsed_time_and_caps_at_capacity() {
+        let limiter = RateLimiter::new(5, 10, 0);
+
+        {
+            let mut bucket = limiter.inner.lock().await;
+            bucket.tokens = 0.0;
+            bucket.last_refill = Instant::now() - Duration::from_millis(200);
+        }
+
+        let mut bucket = limiter.inner.lock().await;
+        limiter.refill(&mut bucket);
+
+        // 200ms at 10 tokens/sec refills ~2 tokens.
+        assert!((bucket.tokens - 2.0).abs() < 0.1);
+
+        bucket.last_refill = Instant::now() - Duration::from_secs(10);
+        limiter.refill(&mut bucket);
+        assert_eq!(bucket.tokens, 5.0);
+    }
+
+    #[tokio::test]
+    async fn drain_empties_the_bucket() {
+        let limiter = RateLimiter::new(5, 10, 0);
+
+        limiter.drain().await;
+
+        let bucket = limiter.inner.lock().await;
+        assert_eq!(bucket.tokens, 0.0);
+    }
+
+    #[tokio::test]
+    async fn drain_holds_the_bucket_empty_for_the_full_cooldown() {
+        let limiter = RateLimiter::new(5, 1000, 60);
+
+        limiter.drain().await;
+
+        // Even though refill_per_sec is high enough to refill instantly,
+        // the cooldown must keep acquire() from succeeding right away.
+        let result = tokio::time::timeout(Duration::from_millis(250), limiter.acquire()).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn acquire_does_not_panic_when_refill_rate_is_zero() {
+        let limiter = RateLimiter::new(1, 0, 0);
+
+        limiter.acquire().await;
+
+        // No tokens left and nothing will ever refill them; acquiring again
+        // must poll instead of dividing by zero.
+        let result = tokio::time::timeout(Duration::from_millis(250), limiter.acquire()).await;
+        assert!(result.is_err());
+    }
+}
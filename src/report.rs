@@ -0,0 +1,26 @@
+use serde::Serialize;
+use std::path::Path;
+
+/// A single rank change the bot would have made, for the dry-run CSV report.
+#[derive(Debug, Serialize)]
+pub struct PlannedChange {
+    pub group_id: u64,
+    pub user_id: u64,
+    pub current_role: String,
+    pub target_role: String,
+    pub account_year: u64,
+    pub match_kind: &'static str,
+}
+
+/// Serializes `changes` to a CSV file at `path`, overwriting it if present.
+pub fn write_report(path: &Path, changes: &[PlannedChange]) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+
+    for change in changes {
+        writer.serialize(change)?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}